@@ -1,4 +1,6 @@
 use std::path::Path;
+use std::collections::HashMap;
+use std::fs;
 
 use rocket::Data;
 use rocket::http::ContentType;
@@ -10,6 +12,8 @@ use multipart::server::save::SavedData;
 
 use data_encoding::HEXLOWER;
 
+use chrono::Utc;
+
 use db::DbConn;
 use db::models::*;
 
@@ -17,6 +21,7 @@ use util;
 use crypto;
 
 use api::{self, PasswordData, JsonResult, EmptyResult};
+use api::notifications::{Notify, UpdateType};
 use auth::Headers;
 
 use CONFIG;
@@ -29,7 +34,7 @@ fn sync(headers: Headers, conn: DbConn) -> JsonResult {
     let folders_json: Vec<Value> = folders.iter().map(|c| c.to_json()).collect();
 
     let ciphers = Cipher::find_by_user(&headers.user.uuid, &conn);
-    let ciphers_json: Vec<Value> = ciphers.iter().map(|c| c.to_json(&headers.host, &conn)).collect();
+    let ciphers_json: Vec<Value> = ciphers.iter().map(|c| cipher_to_json(c, &headers.host, &conn)).collect();
 
     let domains_json = api::core::get_eq_domains(headers).unwrap().into_inner();
 
@@ -45,9 +50,13 @@ fn sync(headers: Headers, conn: DbConn) -> JsonResult {
 
 #[get("/ciphers")]
 fn get_ciphers(headers: Headers, conn: DbConn) -> JsonResult {
-    let ciphers = Cipher::find_by_user(&headers.user.uuid, &conn);
+    // Trashed ciphers only show up in the trash folder via `/sync`, not here.
+    let ciphers: Vec<_> = Cipher::find_by_user(&headers.user.uuid, &conn)
+        .into_iter()
+        .filter(|c| c.deleted_at.is_none())
+        .collect();
 
-    let ciphers_json: Vec<Value> = ciphers.iter().map(|c| c.to_json(&headers.host, &conn)).collect();
+    let ciphers_json: Vec<Value> = ciphers.iter().map(|c| cipher_to_json(c, &headers.host, &conn)).collect();
 
     Ok(Json(json!({
       "Data": ciphers_json,
@@ -62,11 +71,11 @@ fn get_cipher(uuid: String, headers: Headers, conn: DbConn) -> JsonResult {
         None => err!("Cipher doesn't exist")
     };
 
-    if cipher.user_uuid != headers.user.uuid {
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
         err!("Cipher is not owned by user")
     }
 
-    Ok(Json(cipher.to_json(&headers.host, &conn)))
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
 }
 
 #[derive(Deserialize, Debug)]
@@ -96,10 +105,13 @@ struct CipherData {
     identity: Option<Value>,
 
     favorite: bool,
+
+    // Only used when sharing several existing ciphers at once, ignored otherwise
+    id: Option<String>,
 }
 
 #[post("/ciphers", data = "<data>")]
-fn post_ciphers(data: Json<CipherData>, headers: Headers, conn: DbConn) -> JsonResult {
+fn post_ciphers(data: Json<CipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
     let data: CipherData = data.into_inner();
 
     let user_uuid = headers.user.uuid.clone();
@@ -109,7 +121,9 @@ fn post_ciphers(data: Json<CipherData>, headers: Headers, conn: DbConn) -> JsonR
     update_cipher_from_data(&mut cipher, data, &headers, &conn)?;
     cipher.save(&conn);
 
-    Ok(Json(cipher.to_json(&headers.host, &conn)))
+    nt.send_cipher_update(UpdateType::CipherCreate, &cipher, &conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
 }
 
 fn update_cipher_from_data(cipher: &mut Cipher, data: CipherData, headers: &Headers, conn: &DbConn) -> EmptyResult {
@@ -198,6 +212,20 @@ fn copy_values(from: &Value, to: &mut Value) -> bool {
     true
 }
 
+// Wraps `Cipher::to_json` to also surface the trash state, since a
+// soft-deleted cipher still needs to come back over `/sync` so the client
+// can render it in the trash folder.
+fn cipher_to_json(cipher: &Cipher, host: &str, conn: &DbConn) -> Value {
+    let mut json = cipher.to_json(host, conn);
+
+    json["DeletedDate"] = match cipher.deleted_at {
+        Some(deleted_at) => json!(util::format_date(&deleted_at)),
+        None => Value::Null
+    };
+
+    json
+}
+
 use super::folders::FolderData;
 
 #[derive(Deserialize)]
@@ -219,18 +247,18 @@ struct RelationsData {
 
 
 #[post("/ciphers/import", data = "<data>")]
-fn post_ciphers_import(data: Json<ImportData>, headers: Headers, conn: DbConn) -> EmptyResult {
+fn post_ciphers_import(data: Json<ImportData>, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
     let data: ImportData = data.into_inner();
 
     // Read and create the folders
     let folders: Vec<_> = data.folders.iter().map(|folder| {
         let mut folder = Folder::new(headers.user.uuid.clone(), folder.name.clone());
         folder.save(&conn);
+        nt.send_folder_update(UpdateType::FolderCreate, &folder);
         folder
     }).collect();
 
     // Read the relations between folders and ciphers
-    use std::collections::HashMap;
     let mut relations_map = HashMap::new();
 
     for relation in data.folderRelationships {
@@ -252,6 +280,8 @@ fn post_ciphers_import(data: Json<ImportData>, headers: Headers, conn: DbConn) -
         cipher.folder_uuid = folder_uuid;
 
         cipher.save(&conn);
+        nt.send_cipher_update(UpdateType::CipherCreate, &cipher, &conn);
+
         index += 1;
     }
 
@@ -259,12 +289,12 @@ fn post_ciphers_import(data: Json<ImportData>, headers: Headers, conn: DbConn) -
 }
 
 #[post("/ciphers/<uuid>", data = "<data>")]
-fn post_cipher(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbConn) -> JsonResult {
-    put_cipher(uuid, data, headers, conn)
+fn post_cipher(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    put_cipher(uuid, data, headers, conn, nt)
 }
 
 #[put("/ciphers/<uuid>", data = "<data>")]
-fn put_cipher(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbConn) -> JsonResult {
+fn put_cipher(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
     let data: CipherData = data.into_inner();
 
     let mut cipher = match Cipher::find_by_uuid(&uuid, &conn) {
@@ -272,7 +302,7 @@ fn put_cipher(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbCo
         None => err!("Cipher doesn't exist")
     };
 
-    if cipher.user_uuid != headers.user.uuid {
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
         err!("Cipher is not owned by user")
     }
 
@@ -281,27 +311,228 @@ fn put_cipher(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbCo
     update_cipher_from_data(&mut cipher, data, &headers, &conn)?;
     cipher.save(&conn);
 
-    Ok(Json(cipher.to_json(&headers.host, &conn)))
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, &conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
 }
 
 
-#[post("/ciphers/<uuid>/attachment", format = "multipart/form-data", data = "<data>")]
-fn post_attachment(uuid: String, data: Data, content_type: &ContentType, headers: Headers, conn: DbConn) -> JsonResult {
-    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct ShareCipherData {
+    cipher: CipherData,
+    collectionIds: Vec<String>,
+    // Attachment uuid -> attachment key re-encrypted with the organization key.
+    // Required whenever the cipher being shared has attachments, otherwise
+    // they'd be left encrypted under a key nobody can use anymore.
+    attachments: Option<HashMap<String, String>>,
+}
+
+#[post("/ciphers/<uuid>/share", data = "<data>")]
+fn post_cipher_share(uuid: String, data: Json<ShareCipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    share_cipher_by_uuid(&uuid, data, &headers, &conn, &nt)
+}
+
+#[put("/ciphers/<uuid>/share", data = "<data>")]
+fn put_cipher_share(uuid: String, data: Json<ShareCipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    share_cipher_by_uuid(&uuid, data, &headers, &conn, &nt)
+}
+
+fn share_cipher_by_uuid(uuid: &str, data: Json<ShareCipherData>, headers: &Headers, conn: &DbConn, nt: &Notify) -> JsonResult {
+    let data: ShareCipherData = data.into_inner();
+
+    let mut cipher = match Cipher::find_by_uuid(uuid, conn) {
         Some(cipher) => cipher,
         None => err!("Cipher doesn't exist")
     };
 
-    if cipher.user_uuid != headers.user.uuid {
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
         err!("Cipher is not owned by user")
     }
 
+    let organization_uuid = match data.cipher.organizationId {
+        Some(ref org_id) => org_id.clone(),
+        None => err!("Organization id not provided")
+    };
+
+    if UserOrganization::find_by_user_and_org(&headers.user.uuid, &organization_uuid, conn).is_none() {
+        err!("User not part of organization")
+    }
+
+    update_cipher_from_data(&mut cipher, data.cipher, headers, conn)?;
+
+    let attachment_keys = data.attachments.unwrap_or_default();
+
+    // Run the ownership transfer, attachment re-keying and collection
+    // assignment as one transaction: if any attachment is missing its
+    // re-encrypted key, the whole share is rolled back instead of leaving the
+    // cipher owned by the org with undecryptable attachments.
+    conn.transaction(|| -> EmptyResult {
+        // After updating, mark the cipher as belonging to the organization instead of the user
+        cipher.organization_uuid = Some(organization_uuid);
+        cipher.user_uuid = None;
+        cipher.save(conn);
+
+        share_cipher_attachment_keys(&cipher, &attachment_keys, conn)?;
+        share_cipher_attach_collections(&cipher, &data.collectionIds, conn)?;
+
+        Ok(())
+    })?;
+
+    nt.send_cipher_update(UpdateType::CipherShare, &cipher, conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, conn)))
+}
+
+// Replaces the stored key of every attachment belonging to `cipher` with the
+// one the client re-encrypted under the organization key, so the files stay
+// decryptable once the cipher's `user_uuid` is cleared.
+fn share_cipher_attachment_keys(cipher: &Cipher, attachment_keys: &HashMap<String, String>, conn: &DbConn) -> EmptyResult {
+    for mut attachment in Attachment::find_by_cipher(&cipher.uuid, conn) {
+        match attachment_keys.get(&attachment.id) {
+            Some(new_key) => {
+                attachment.akey = Some(new_key.clone());
+                attachment.save(conn);
+            }
+            None => err!("Missing re-encrypted key for attachment")
+        }
+    }
+
+    Ok(())
+}
+
+fn share_cipher_attach_collections(cipher: &Cipher, collection_ids: &[String], conn: &DbConn) -> EmptyResult {
+    let organization_uuid = match cipher.organization_uuid {
+        Some(ref org_uuid) => org_uuid,
+        None => err!("Cipher doesn't belong to an organization")
+    };
+
+    for col_id in collection_ids {
+        match Collection::find_by_uuid(col_id, conn) {
+            None => err!("Invalid collection ID provided"),
+            Some(collection) => {
+                if &collection.org_uuid != organization_uuid {
+                    err!("Collection and cipher organization mismatch")
+                }
+
+                CollectionCipher::save(&cipher.uuid, &collection.uuid, conn);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct ShareSelectedCipherData {
+    ciphers: Vec<CipherData>,
+    collectionIds: Vec<String>,
+    // Attachment uuid -> attachment key re-encrypted with the organization key,
+    // flattened across every cipher in the request (attachment uuids are unique).
+    attachments: Option<HashMap<String, String>>,
+}
+
+#[put("/ciphers/share", data = "<data>")]
+fn put_cipher_share_selected(data: Json<ShareSelectedCipherData>, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let data: ShareSelectedCipherData = data.into_inner();
+
+    if data.ciphers.is_empty() {
+        err!("You must select at least one cipher")
+    }
+
+    if data.collectionIds.is_empty() {
+        err!("You must select at least one collection")
+    }
+
+    let attachment_keys = data.attachments.unwrap_or_default();
+
+    // One transaction for the whole batch: a cipher missing an attachment key
+    // (or any other failure partway through) rolls every cipher in the
+    // request back, instead of leaving some already transferred to the org.
+    let mut shared_ciphers = Vec::new();
+
+    conn.transaction(|| -> EmptyResult {
+        for cipher_data in data.ciphers {
+            let uuid = match cipher_data.id {
+                Some(ref id) => id.clone(),
+                None => err!("Cipher id not provided")
+            };
+
+            let mut cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+                Some(cipher) => cipher,
+                None => err!("Cipher doesn't exist")
+            };
+
+            if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+                err!("Cipher is not owned by user")
+            }
+
+            let organization_uuid = match cipher_data.organizationId {
+                Some(ref org_id) => org_id.clone(),
+                None => err!("Organization id not provided")
+            };
+
+            if UserOrganization::find_by_user_and_org(&headers.user.uuid, &organization_uuid, &conn).is_none() {
+                err!("User not part of organization")
+            }
+
+            update_cipher_from_data(&mut cipher, cipher_data, &headers, &conn)?;
+
+            cipher.organization_uuid = Some(organization_uuid);
+            cipher.user_uuid = None;
+            cipher.save(&conn);
+
+            share_cipher_attachment_keys(&cipher, &attachment_keys, &conn)?;
+            share_cipher_attach_collections(&cipher, &data.collectionIds, &conn)?;
+
+            shared_ciphers.push(cipher);
+        }
+
+        Ok(())
+    })?;
+
+    for cipher in &shared_ciphers {
+        nt.send_cipher_update(UpdateType::CipherShare, cipher, &conn);
+    }
+
+    Ok(())
+}
+
+// Checks that adding `new_size` bytes would not push the user over their
+// configured total attachment storage limit.
+fn check_storage_limit(user_uuid: &str, new_size: i64, conn: &DbConn) -> EmptyResult {
+    if let Some(limit) = CONFIG.user_attachment_limit {
+        let already_used = Attachment::size_by_user(user_uuid, conn);
+
+        if already_used + new_size > limit {
+            err!("Attachment storage limit reached! Delete some attachments to free up space")
+        }
+    }
+
+    Ok(())
+}
+
+// Shared by `post_attachment` and `post_attachment_admin`: streams a new
+// attachment's multipart data to disk, capped at the user's remaining
+// storage quota, and saves the resulting `Attachment` row.
+fn upload_new_attachment(cipher: &Cipher, user_uuid: &str, content_type: &ContentType, data: Data, conn: &DbConn) -> Result<(), &'static str> {
     let mut params = content_type.params();
     let boundary_pair = params.next().expect("No boundary provided");
     let boundary = boundary_pair.1;
 
     let base_path = Path::new(&CONFIG.attachments_folder).join(&cipher.uuid);
 
+    // The actual upload size isn't known until we've read it, so cap the save
+    // itself at whatever headroom the user has left instead of checking the
+    // quota only after the bytes are already on disk.
+    let remaining_quota = CONFIG.user_attachment_limit.map(|limit| {
+        let already_used = Attachment::size_by_user(user_uuid, conn);
+        (limit - already_used).max(0) as u64
+    });
+
+    let mut error: Option<&'static str> = None;
+
     Multipart::with_body(data.open(), boundary).foreach_entry(|mut field| {
         let name = field.headers.filename.unwrap(); // This is provided by the client, don't trust it
 
@@ -310,27 +541,199 @@ fn post_attachment(uuid: String, data: Data, content_type: &ContentType, headers
 
         let size = match field.data.save()
             .memory_threshold(0)
-            .size_limit(None)
-            .with_path(path) {
+            .size_limit(remaining_quota)
+            .with_path(&path) {
             SaveResult::Full(SavedData::File(_, size)) => size as i32,
-            _ => return
+            SaveResult::Partial(SavedData::File(_, _), _) => {
+                // Ran over the remaining quota: discard the truncated file.
+                fs::remove_file(&path).ok();
+                error = Some("Attachment storage limit reached! Delete some attachments to free up space");
+                return
+            }
+            _ => {
+                error = Some("Error saving attachment data");
+                return
+            }
         };
 
-        let attachment = Attachment::new(file_name, cipher.uuid.clone(), name, size);
-        println!("Attachment: {:#?}", attachment);
+        // Re-check the quota and save the attachment row as one transaction,
+        // so two uploads racing past the pre-check above can't both commit
+        // and push the user over the limit together.
+        let saved = conn.transaction(|| -> EmptyResult {
+            check_storage_limit(user_uuid, size as i64, conn)?;
+
+            let attachment = Attachment::new(file_name, cipher.uuid.clone(), name, size);
+            attachment.save(conn);
+            Ok(())
+        });
+
+        if saved.is_err() {
+            fs::remove_file(&path).ok();
+            error = Some("Attachment storage limit reached! Delete some attachments to free up space");
+        }
+    }).expect("Error processing multipart data");
+
+    match error {
+        Some(msg) => Err(msg),
+        None => Ok(())
+    }
+}
+
+#[post("/ciphers/<uuid>/attachment", format = "multipart/form-data", data = "<data>")]
+fn post_attachment(uuid: String, data: Data, content_type: &ContentType, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+        err!("Cipher is not owned by user")
+    }
+
+    if let Err(msg) = upload_new_attachment(&cipher, &headers.user.uuid, content_type, data, &conn) {
+        err!(msg)
+    }
+
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, &conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct AttachmentRequestData {
+    key: String,
+    fileName: String,
+    fileSize: i64,
+}
+
+// Step one of the v2 attachment flow: register the (already encrypted)
+// metadata and hand back an id and URL the client uploads the file bytes to.
+#[post("/ciphers/<uuid>/attachment/v2", data = "<data>")]
+fn post_attachment_v2(uuid: String, data: Json<AttachmentRequestData>, headers: Headers, conn: DbConn) -> JsonResult {
+    let data: AttachmentRequestData = data.into_inner();
+
+    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+        err!("Cipher is not owned by user")
+    }
+
+    let attachment_id = HEXLOWER.encode(&crypto::get_random(vec![0; 10]));
+
+    // Check the quota and register the attachment as one transaction, so
+    // two concurrent v2 registrations can't both read the same pre-insert
+    // size_by_user sum and jointly push the user over their limit.
+    conn.transaction(|| -> EmptyResult {
+        check_storage_limit(&headers.user.uuid, data.fileSize, &conn)?;
+
+        let mut attachment = Attachment::new(attachment_id.clone(), cipher.uuid.clone(), data.fileName, data.fileSize as i32);
+        attachment.akey = Some(data.key);
         attachment.save(&conn);
+
+        Ok(())
+    })?;
+
+    Ok(Json(json!({
+        "Object": "attachment-fileUpload",
+        "AttachmentId": attachment_id,
+        "Url": format!("/ciphers/{}/attachment/{}", cipher.uuid, attachment_id),
+        "FileUploadType": 0,
+    })))
+}
+
+// Step two of the v2 attachment flow: stream the file bytes into the slot
+// that was registered by `post_attachment_v2`.
+#[post("/ciphers/<uuid>/attachment/<attachment_id>", format = "multipart/form-data", data = "<data>")]
+fn post_attachment_v2_data(uuid: String, attachment_id: String, data: Data, content_type: &ContentType, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let attachment = match Attachment::find_by_id(&attachment_id, &conn) {
+        Some(attachment) => attachment,
+        None => err!("Attachment doesn't exist")
+    };
+
+    if attachment.cipher_uuid != uuid {
+        err!("Attachment from other cipher")
+    }
+
+    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+        err!("Cipher is not owned by user")
+    }
+
+    let mut params = content_type.params();
+    let boundary_pair = params.next().expect("No boundary provided");
+    let boundary = boundary_pair.1;
+
+    let path = Path::new(&CONFIG.attachments_folder).join(&cipher.uuid).join(&attachment.id);
+
+    let mut error = false;
+
+    Multipart::with_body(data.open(), boundary).foreach_entry(|mut field| {
+        match field.data.save()
+            .memory_threshold(0)
+            .size_limit(Some(attachment.file_size as u64))
+            .with_path(&path) {
+            SaveResult::Full(SavedData::File(_, size)) if size == attachment.file_size as u64 => (),
+            // Either more or fewer bytes than the declared fileSize were sent:
+            // drop the mismatched data instead of trusting it.
+            _ => {
+                fs::remove_file(&path).ok();
+                error = true;
+            }
+        }
     }).expect("Error processing multipart data");
 
-    Ok(Json(cipher.to_json(&headers.host, &conn)))
+    if error {
+        err!("Attachment data doesn't match the declared size")
+    }
+
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, &conn);
+
+    Ok(())
+}
+
+#[get("/ciphers/<uuid>/attachment/<attachment_id>")]
+fn get_attachment(uuid: String, attachment_id: String, headers: Headers, conn: DbConn) -> JsonResult {
+    let attachment = match Attachment::find_by_id(&attachment_id, &conn) {
+        Some(attachment) => attachment,
+        None => err!("Attachment doesn't exist")
+    };
+
+    if attachment.cipher_uuid != uuid {
+        err!("Attachment from other cipher")
+    }
+
+    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+        err!("Cipher is not owned by user")
+    }
+
+    Ok(Json(json!({
+        "Object": "attachment-fileUpload",
+        "Id": attachment.id,
+        "Url": format!("{}/attachments/{}/{}", headers.host, cipher.uuid, attachment.id),
+        "FileName": attachment.file_name,
+    })))
 }
 
 #[post("/ciphers/<uuid>/attachment/<attachment_id>/delete")]
-fn delete_attachment_post(uuid: String, attachment_id: String, headers: Headers, conn: DbConn) -> EmptyResult {
-    delete_attachment(uuid, attachment_id, headers, conn)
+fn delete_attachment_post(uuid: String, attachment_id: String, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    delete_attachment(uuid, attachment_id, headers, conn, nt)
 }
 
 #[delete("/ciphers/<uuid>/attachment/<attachment_id>")]
-fn delete_attachment(uuid: String, attachment_id: String, headers: Headers, conn: DbConn) -> EmptyResult {
+fn delete_attachment(uuid: String, attachment_id: String, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
     let attachment = match Attachment::find_by_id(&attachment_id, &conn) {
         Some(attachment) => attachment,
         None => err!("Attachment doesn't exist")
@@ -345,29 +748,56 @@ fn delete_attachment(uuid: String, attachment_id: String, headers: Headers, conn
         None => err!("Cipher doesn't exist")
     };
 
-    if cipher.user_uuid != headers.user.uuid {
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
         err!("Cipher is not owned by user")
     }
 
     // Delete attachment
     attachment.delete(&conn);
 
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, &conn);
+
     Ok(())
 }
 
 #[post("/ciphers/<uuid>/delete")]
-fn delete_cipher_post(uuid: String, headers: Headers, conn: DbConn) -> EmptyResult {
-    delete_cipher(uuid, headers, conn)
+fn delete_cipher_post(uuid: String, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    delete_cipher(uuid, headers, conn, nt)
 }
 
 #[delete("/ciphers/<uuid>")]
-fn delete_cipher(uuid: String, headers: Headers, conn: DbConn) -> EmptyResult {
+fn delete_cipher(uuid: String, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let mut cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+        err!("Cipher is not owned by user")
+    }
+
+    // Soft delete: the cipher moves to the trash instead of being removed
+    // outright, so the client can offer a restore option.
+    cipher.deleted_at = Some(Utc::now().naive_utc());
+    cipher.save(&conn);
+
+    nt.send_cipher_update(UpdateType::CipherDelete, &cipher, &conn);
+
+    Ok(())
+}
+
+// Permanently removes a cipher and its attachments, bypassing the trash.
+// This is reached from the org admin's trash view, so a shared cipher (whose
+// `user_uuid` is already `None`) is authorized the same way the other
+// "-admin" routes are: by org ownership/admin role, not personal ownership.
+#[post("/ciphers/<uuid>/delete-admin")]
+fn delete_cipher_permanent(uuid: String, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
     let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
         Some(cipher) => cipher,
         None => err!("Cipher doesn't exist")
     };
 
-    if cipher.user_uuid != headers.user.uuid {
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) && !user_can_edit_cipher(&cipher, &headers, &conn) {
         err!("Cipher is not owned by user")
     }
 
@@ -377,19 +807,173 @@ fn delete_cipher(uuid: String, headers: Headers, conn: DbConn) -> EmptyResult {
     // Delete cipher
     cipher.delete(&conn);
 
+    nt.send_cipher_update(UpdateType::CipherDelete, &cipher, &conn);
+
+    Ok(())
+}
+
+#[put("/ciphers/<uuid>/restore")]
+fn restore_cipher_put(uuid: String, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    restore_cipher_by_uuid(&uuid, &headers, &conn, &nt)
+}
+
+fn restore_cipher_by_uuid(uuid: &str, headers: &Headers, conn: &DbConn, nt: &Notify) -> JsonResult {
+    let mut cipher = match Cipher::find_by_uuid(uuid, conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+        err!("Cipher is not owned by user")
+    }
+
+    cipher.deleted_at = None;
+    cipher.save(conn);
+
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, conn)))
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct RestoreSelectedData {
+    Ids: Vec<String>,
+}
+
+#[put("/ciphers/restore", data = "<data>")]
+fn restore_cipher_selected(data: Json<RestoreSelectedData>, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let data: RestoreSelectedData = data.into_inner();
+
+    // Collect the restored ciphers so we can notify after the transaction
+    // commits, once we know none of them were rolled back.
+    let mut restored_ciphers = Vec::new();
+
+    conn.transaction(|| {
+        for uuid in &data.Ids {
+            let mut cipher = match Cipher::find_by_uuid(uuid, &conn) {
+                Some(cipher) => cipher,
+                None => err!("Cipher doesn't exist")
+            };
+
+            if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+                err!("Cipher is not owned by user")
+            }
+
+            cipher.deleted_at = None;
+            cipher.save(&conn);
+
+            restored_ciphers.push(cipher);
+        }
+
+        Ok(())
+    })?;
+
+    for cipher in &restored_ciphers {
+        nt.send_cipher_update(UpdateType::CipherUpdate, cipher, &conn);
+    }
+
     Ok(())
 }
 
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct SelectedCipherData {
+    Ids: Vec<String>,
+}
+
 #[post("/ciphers/delete", data = "<data>")]
-fn delete_cipher_selected(data: Json<Value>, headers: Headers, conn: DbConn) -> EmptyResult {
-    let data: Value = data.into_inner();
+fn delete_cipher_selected(data: Json<SelectedCipherData>, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let data: SelectedCipherData = data.into_inner();
+
+    // Collect the soft-deleted ciphers so we can notify after the
+    // transaction commits, once we know none of them were rolled back.
+    let mut deleted_ciphers = Vec::new();
+
+    conn.transaction(|| {
+        for uuid in &data.Ids {
+            let mut cipher = match Cipher::find_by_uuid(uuid, &conn) {
+                Some(cipher) => cipher,
+                None => err!("Cipher doesn't exist")
+            };
+
+            if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+                err!("Cipher is not owned by user")
+            }
+
+            // Soft delete, same as the single-item endpoint, so bulk-deleting
+            // several ciphers is just as recoverable as deleting one.
+            cipher.deleted_at = Some(Utc::now().naive_utc());
+            cipher.save(&conn);
 
-    println!("{:#?}", data);
-    unimplemented!()
+            deleted_ciphers.push(cipher);
+        }
+
+        Ok(())
+    })?;
+
+    for cipher in &deleted_ciphers {
+        nt.send_cipher_update(UpdateType::CipherDelete, cipher, &conn);
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct MoveCipherSelectedData {
+    Ids: Vec<String>,
+    FolderId: Option<String>,
+}
+
+#[post("/ciphers/move", data = "<data>")]
+fn move_cipher_selected(data: Json<MoveCipherSelectedData>, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let data: MoveCipherSelectedData = data.into_inner();
+
+    if let Some(ref folder_id) = data.FolderId {
+        match Folder::find_by_uuid(folder_id, &conn) {
+            Some(folder) => {
+                if folder.user_uuid != headers.user.uuid {
+                    err!("Folder is not owned by user")
+                }
+            }
+            None => err!("Folder doesn't exist")
+        }
+    }
+
+    // Collect the moved ciphers so we can notify after the transaction
+    // commits, once we know none of them were rolled back.
+    let mut moved_ciphers = Vec::new();
+
+    conn.transaction(|| {
+        for uuid in &data.Ids {
+            let mut cipher = match Cipher::find_by_uuid(uuid, &conn) {
+                Some(cipher) => cipher,
+                None => err!("Cipher doesn't exist")
+            };
+
+            if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) {
+                err!("Cipher is not owned by user")
+            }
+
+            cipher.folder_uuid = data.FolderId.clone();
+            cipher.save(&conn);
+
+            moved_ciphers.push(cipher);
+        }
+
+        Ok(())
+    })?;
+
+    for cipher in &moved_ciphers {
+        nt.send_cipher_update(UpdateType::CipherUpdate, cipher, &conn);
+    }
+
+    Ok(())
 }
 
 #[post("/ciphers/purge", data = "<data>")]
-fn delete_all(data: Json<PasswordData>, headers: Headers, conn: DbConn) -> EmptyResult {
+fn delete_all(data: Json<PasswordData>, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
     let data: PasswordData = data.into_inner();
     let password_hash = data.masterPasswordHash;
 
@@ -404,10 +988,133 @@ fn delete_all(data: Json<PasswordData>, headers: Headers, conn: DbConn) -> Empty
         for a in Attachment::find_by_cipher(&cipher.uuid, &conn) { a.delete(&conn); }
 
         cipher.delete(&conn);
+        nt.send_cipher_update(UpdateType::CipherDelete, &cipher, &conn);
     }
 
     // Delete folders
-    for f in Folder::find_by_user(&user.uuid, &conn) { f.delete(&conn); }
+    for f in Folder::find_by_user(&user.uuid, &conn) {
+        f.delete(&conn);
+        nt.send_folder_update(UpdateType::FolderDelete, &f);
+    }
 
     Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Admin routes: org owners/admins can manage ciphers they don't personally
+// own, as long as the cipher belongs to their organization.
+// ---------------------------------------------------------------------------
+
+fn user_can_edit_cipher(cipher: &Cipher, headers: &Headers, conn: &DbConn) -> bool {
+    let org_uuid = match cipher.organization_uuid {
+        Some(ref org_uuid) => org_uuid,
+        None => return false
+    };
+
+    match UserOrganization::find_by_user_and_org(&headers.user.uuid, org_uuid, conn) {
+        Some(user_org) => user_org.type_ == UserOrgType::Owner || user_org.type_ == UserOrgType::Admin,
+        None => false
+    }
+}
+
+#[get("/ciphers/<uuid>/admin")]
+fn get_cipher_admin(uuid: String, headers: Headers, conn: DbConn) -> JsonResult {
+    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) && !user_can_edit_cipher(&cipher, &headers, &conn) {
+        err!("Cipher is not owned by user")
+    }
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
+}
+
+#[post("/ciphers/admin", data = "<data>")]
+fn post_cipher_admin(data: Json<CipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    let data: CipherData = data.into_inner();
+
+    let organization_uuid = match data.organizationId {
+        Some(ref org_id) => org_id.clone(),
+        None => err!("Organization id not provided")
+    };
+
+    if UserOrganization::find_by_user_and_org(&headers.user.uuid, &organization_uuid, &conn)
+        .map_or(true, |user_org| user_org.type_ != UserOrgType::Owner && user_org.type_ != UserOrgType::Admin) {
+        err!("Only org owners/admins can create ciphers this way")
+    }
+
+    let mut cipher = Cipher::new(headers.user.uuid.clone(), data.type_, data.name.clone(), data.favorite);
+    cipher.organization_uuid = Some(organization_uuid);
+    cipher.user_uuid = None;
+
+    update_cipher_from_data(&mut cipher, data, &headers, &conn)?;
+    cipher.save(&conn);
+
+    nt.send_cipher_update(UpdateType::CipherCreate, &cipher, &conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
+}
+
+#[put("/ciphers/<uuid>/admin", data = "<data>")]
+fn put_cipher_admin(uuid: String, data: Json<CipherData>, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    let data: CipherData = data.into_inner();
+
+    let mut cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) && !user_can_edit_cipher(&cipher, &headers, &conn) {
+        err!("Cipher is not owned by user")
+    }
+
+    cipher.favorite = data.favorite;
+
+    update_cipher_from_data(&mut cipher, data, &headers, &conn)?;
+    cipher.save(&conn);
+
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, &conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
+}
+
+#[delete("/ciphers/<uuid>/admin")]
+fn delete_cipher_admin(uuid: String, headers: Headers, conn: DbConn, nt: Notify) -> EmptyResult {
+    let mut cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) && !user_can_edit_cipher(&cipher, &headers, &conn) {
+        err!("Cipher is not owned by user")
+    }
+
+    cipher.deleted_at = Some(Utc::now().naive_utc());
+    cipher.save(&conn);
+
+    nt.send_cipher_update(UpdateType::CipherDelete, &cipher, &conn);
+
+    Ok(())
+}
+
+#[post("/ciphers/<uuid>/attachment/admin", format = "multipart/form-data", data = "<data>")]
+fn post_attachment_admin(uuid: String, data: Data, content_type: &ContentType, headers: Headers, conn: DbConn, nt: Notify) -> JsonResult {
+    let cipher = match Cipher::find_by_uuid(&uuid, &conn) {
+        Some(cipher) => cipher,
+        None => err!("Cipher doesn't exist")
+    };
+
+    if cipher.user_uuid.as_ref() != Some(&headers.user.uuid) && !user_can_edit_cipher(&cipher, &headers, &conn) {
+        err!("Cipher is not owned by user")
+    }
+
+    if let Err(msg) = upload_new_attachment(&cipher, &headers.user.uuid, content_type, data, &conn) {
+        err!(msg)
+    }
+
+    nt.send_cipher_update(UpdateType::CipherUpdate, &cipher, &conn);
+
+    Ok(Json(cipher_to_json(&cipher, &headers.host, &conn)))
 }
\ No newline at end of file
@@ -0,0 +1,176 @@
+// NOTE: `main.rs` must call `.manage(notifications::start_notification_server())`
+// when assembling the Rocket instance, so the `State<WebSocketUsers>` the
+// `Notify` guard below relies on actually resolves.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use rocket::{Outcome, State};
+use rocket::request::{self, FromRequest, Request};
+
+use rocket_contrib::Value;
+
+use url::form_urlencoded;
+use ws::{self, CloseCode, Handler, Handshake};
+
+use db::DbConn;
+use db::models::{Cipher, Folder, UserOrganization};
+
+use auth::{self, Headers};
+
+use CONFIG;
+
+#[derive(Serialize, Copy, Clone)]
+pub enum UpdateType {
+    CipherUpdate = 0,
+    CipherCreate = 1,
+    CipherDelete = 2,
+    CipherShare = 3,
+
+    FolderCreate = 8,
+    FolderUpdate = 9,
+    FolderDelete = 10,
+}
+
+// Keeps track of the currently connected clients, a list of websocket senders per user.
+// The key is the user uuid, since a user can have several devices connected at once.
+pub struct WebSocketUsers {
+    map: Arc<RwLock<HashMap<String, Vec<ws::Sender>>>>,
+}
+
+impl WebSocketUsers {
+    pub fn new() -> Self {
+        WebSocketUsers { map: Arc::new(RwLock::new(HashMap::new())) }
+    }
+
+    pub fn register(&self, user_uuid: String, sender: ws::Sender) {
+        self.map.write().unwrap().entry(user_uuid).or_insert_with(Vec::new).push(sender);
+    }
+
+    pub fn unregister(&self, user_uuid: &str, token: ws::util::Token) {
+        if let Some(senders) = self.map.write().unwrap().get_mut(user_uuid) {
+            senders.retain(|s| s.token() != token);
+        }
+    }
+
+    fn send_update(&self, user_uuid: &str, data: &Value) {
+        if let Some(senders) = self.map.read().unwrap().get(user_uuid) {
+            for sender in senders {
+                sender.send(data.to_string()).ok();
+            }
+        }
+    }
+}
+
+impl Clone for WebSocketUsers {
+    fn clone(&self) -> Self {
+        WebSocketUsers { map: Arc::clone(&self.map) }
+    }
+}
+
+// One `WsHandler` is created per incoming connection by `ws::listen`. It
+// authenticates the connection's access token during the handshake and keeps
+// the user uuid around so it can unregister the right sender on close.
+struct WsHandler {
+    users: WebSocketUsers,
+    user_uuid: Option<String>,
+    sender: ws::Sender,
+}
+
+impl Handler for WsHandler {
+    fn on_open(&mut self, handshake: Handshake) -> ws::Result<()> {
+        let query = handshake.request.resource()
+            .splitn(2, '?')
+            .nth(1)
+            .unwrap_or("");
+
+        let access_token = form_urlencoded::parse(query.as_bytes())
+            .find(|&(ref key, _)| key == "access_token")
+            .map(|(_, value)| value.into_owned());
+
+        let user_uuid = access_token
+            .and_then(|token| auth::decode_login(&token).ok())
+            .map(|claims| claims.sub);
+
+        match user_uuid {
+            Some(uuid) => {
+                self.users.register(uuid.clone(), self.sender.clone());
+                self.user_uuid = Some(uuid);
+                Ok(())
+            }
+            None => self.sender.close(CloseCode::Policy)
+        }
+    }
+
+    fn on_close(&mut self, _code: CloseCode, _reason: &str) {
+        if let Some(ref uuid) = self.user_uuid {
+            self.users.unregister(uuid, self.sender.token());
+        }
+    }
+}
+
+// Starts the WebSocket server used to push notifications to connected
+// clients in a background thread, and returns the shared user registry so it
+// can be handed to Rocket via `.manage()` for the `Notify` request guard.
+pub fn start_notification_server() -> WebSocketUsers {
+    let users = WebSocketUsers::new();
+    let server_users = users.clone();
+
+    thread::spawn(move || {
+        ws::listen(&CONFIG.websocket_address[..], move |sender| {
+            WsHandler { users: server_users.clone(), user_uuid: None, sender }
+        }).expect("Error starting WebSocket notifications server");
+    });
+
+    users
+}
+
+// Rocket request guard that gives handlers a convenient way to push updates
+// to the users affected by the mutation they just performed.
+pub struct Notify<'a>(State<'a, WebSocketUsers>);
+
+impl<'a> Notify<'a> {
+    pub fn send_cipher_update(&self, ut: UpdateType, cipher: &Cipher, conn: &DbConn) {
+        let payload = json!({
+            "Type": ut,
+            "Id": cipher.uuid,
+            "UserId": cipher.user_uuid,
+            "OrganizationId": cipher.organization_uuid,
+        });
+
+        match cipher.user_uuid {
+            Some(ref user_uuid) => self.0.send_update(user_uuid, &payload),
+            None => {
+                if let Some(ref org_uuid) = cipher.organization_uuid {
+                    for user_org in UserOrganization::find_by_org(org_uuid, conn) {
+                        self.0.send_update(&user_org.user_uuid, &payload);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn send_folder_update(&self, ut: UpdateType, folder: &Folder) {
+        let payload = json!({
+            "Type": ut,
+            "Id": folder.uuid,
+            "UserId": folder.user_uuid,
+        });
+
+        self.0.send_update(&folder.user_uuid, &payload);
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Notify<'a> {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        // Make sure the caller is actually logged in, even though we don't
+        // need anything from the headers themselves.
+        request.guard::<Headers>()?;
+
+        let users = request.guard::<State<WebSocketUsers>>()?;
+        Outcome::Success(Notify(users))
+    }
+}